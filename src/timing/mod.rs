@@ -1,7 +1,7 @@
 //! the subsystem that governs the timing of the game engine
 
 use std::cell::{Cell};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant as StdInstant};
 
 #[cfg(test)]
 mod test;
@@ -18,26 +18,144 @@ pub fn seconds_to_duration (seconds: f64) -> Duration {
     return Duration::from_nanos((seconds * 1_000_000_000f64) as u64);
 }
 
+/// blend a new sample into a running exponential moving average, weighted by `LOOP_STATE_SMOOTHING`
+fn ema (average: f64, sample: f64) -> f64 {
+    return average + LOOP_STATE_SMOOTHING * (sample - average);
+}
+
+/// a point in time as produced by a `TimeSource`
+pub trait TimeInstant: Copy {
+
+    /// the duration that has passed between an earlier instant and this one
+    fn duration_since (&self, earlier: Self) -> Duration;
+
+    /// this instant, moved backwards by `duration`, without panicking when the result would precede what the source can represent
+    fn saturating_sub (&self, duration: Duration) -> Self;
+
+}
+
+impl TimeInstant for StdInstant {
+
+    fn duration_since (&self, earlier: Self) -> Duration {
+        return StdInstant::duration_since(self, earlier);
+    }
+
+    /// returns `self` unmoved when the result cannot be represented as a `std::time::Instant`
+    fn saturating_sub (&self, duration: Duration) -> Self {
+        return self.checked_sub(duration).unwrap_or(*self);
+    }
+
+}
+
+/// a pluggable source of monotonic time, letting `Clock` and `RevLimiter` be driven by
+/// something other than the OS clock (e.g. a manually-stepped source in tests and replays,
+/// or an external monotonic counter such as an audio-sample clock)
+pub trait TimeSource {
+
+    /// the instant type produced by this source
+    type Instant: TimeInstant;
+
+    /// the current instant according to this source
+    fn now (&self) -> Self::Instant;
+
+}
+
+/// the default time source, backed by `std::time::Instant`
+#[derive(Default)]
+pub struct SystemTime;
+
+impl TimeSource for SystemTime {
+
+    type Instant = StdInstant;
+
+    fn now (&self) -> StdInstant {
+        return StdInstant::now();
+    }
+
+}
+
+/// an instant produced by a `ManualClock`, counted as an offset from an arbitrary zero point
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ManualInstant (Duration);
+
+impl TimeInstant for ManualInstant {
+
+    fn duration_since (&self, earlier: Self) -> Duration {
+        return self.0.saturating_sub(earlier.0);
+    }
+
+    fn saturating_sub (&self, duration: Duration) -> Self {
+        return ManualInstant(self.0.saturating_sub(duration));
+    }
+
+}
+
+/// a time source that only advances when told to, for deterministic tests and replays
+#[derive(Default)]
+pub struct ManualClock {
+    now: Cell<ManualInstant>,
+}
+
+impl ManualClock {
+
+    /// create a manual clock starting at time zero
+    pub fn new () -> Self {
+        return Self { now: Cell::new(ManualInstant(Duration::new(0, 0))) };
+    }
+
+    /// move this clock's current time forward by `duration`
+    pub fn advance (&self, duration: Duration) {
+        let ManualInstant(current) = self.now.get();
+        self.now.set(ManualInstant(current + duration));
+    }
+
+}
+
+impl TimeSource for ManualClock {
+
+    type Instant = ManualInstant;
+
+    fn now (&self) -> ManualInstant {
+        return self.now.get();
+    }
+
+}
+
+/// the default period that `elapsed_wrapped()` wraps around, chosen to comfortably outlast most single play sessions
+const DEFAULT_WRAP_PERIOD: Duration = Duration::from_secs(3600);
+
 /// keeps track of the passing of time from a recorded instant
-pub struct Clock {
-    reset_time: Cell<Instant>
+pub struct Clock<T: TimeSource = SystemTime> {
+    source: T,
+    reset_time: Cell<T::Instant>,
+    wrap_period: Duration,
 }
 
-impl Clock {
+impl Clock<SystemTime> {
 
-    /// create a new clock (starting from the moment it's created)
+    /// create a new clock (starting from the moment it's created), backed by the system clock
     pub fn new () -> Self {
-        return Self { reset_time: Cell::new(Instant::now()) };
+        return Self::with_source(SystemTime::default());
+    }
+
+}
+
+impl<T: TimeSource> Clock<T> {
+
+    /// create a new clock (starting from the moment it's created) driven by a custom time source
+    pub fn with_source (source: T) -> Self {
+        let reset_time = Cell::new(source.now());
+        return Self { source, reset_time, wrap_period: DEFAULT_WRAP_PERIOD };
     }
 
     /// reset the clock back to its 0-state (no elapsed time)
     pub fn reset (&self) {
-        self.reset_time.set(Instant::now());
+        self.reset_time.set(self.source.now());
     }
 
     /// get the elasped duration
     pub fn elapsed (&self) -> Duration {
-        return Instant::now() - self.reset_time.get();
+        return self.source.now().duration_since(self.reset_time.get());
     }
 
     /// get the number of elapsed seconds as a 64-bit float
@@ -45,10 +163,167 @@ impl Clock {
         return duration_to_seconds(self.elapsed());
     }
 
+    /// get the elapsed duration wrapped to `wrap_period`, staying small enough to keep full precision
+    /// when narrowed to an `f32` for periodic/looping effects (e.g. animated shader uniforms)
+    pub fn elapsed_wrapped (&self) -> Duration {
+        let wrap_nanos = self.wrap_period.as_nanos();
+        if wrap_nanos == 0 {
+            return Duration::new(0, 0);
+        }
+        return Duration::from_nanos((self.elapsed().as_nanos() % wrap_nanos) as u64);
+    }
+
+    /// get the number of elapsed seconds wrapped to `wrap_period`, as a 64-bit float
+    pub fn elapsed_seconds_wrapped (&self) -> f64 {
+        return duration_to_seconds(self.elapsed_wrapped());
+    }
+
+    /// set the period that `elapsed_wrapped()` wraps around; align this to the period of your longest animation cycle
+    pub fn set_wrap_period (&mut self, wrap_period: Duration) {
+        self.wrap_period = wrap_period;
+    }
+
+}
+
+/// a musical time signature layered on top of a `Clock`, converting elapsed time into beats, bars, and ticks for
+/// rhythm- and music-driven games, letting a `RevLimiter` tick on musical boundaries rather than raw seconds
+pub struct ClockSignature<T: TimeSource = SystemTime> {
+    clock: Clock<T>,
+    nanos_per_beat: f64,
+    ticks_per_beat: u32,
+    beats_per_bar: u32,
+    beat_offset: f64,
+}
+
+impl ClockSignature<SystemTime> {
+
+    /// create a new clock signature at the given tempo, backed by the system clock
+    pub fn new (bpm: f64, ticks_per_beat: u32, beats_per_bar: u32) -> Self {
+        return Self::with_clock(Clock::new(), bpm, ticks_per_beat, beats_per_bar);
+    }
+
+}
+
+impl<T: TimeSource> ClockSignature<T> {
+
+    /// create a new clock signature at the given tempo, driven by a custom clock
+    pub fn with_clock (clock: Clock<T>, bpm: f64, ticks_per_beat: u32, beats_per_bar: u32) -> Self {
+        return Self {
+            clock,
+            nanos_per_beat: Self::nanos_per_beat_from_bpm(bpm),
+            ticks_per_beat,
+            beats_per_bar,
+            beat_offset: 0.0,
+        };
+    }
+
+    /// derive the number of nanoseconds a single beat lasts from a tempo in beats per minute
+    fn nanos_per_beat_from_bpm (bpm: f64) -> f64 {
+        return (60.0 / bpm) * 1_000_000_000.0;
+    }
+
+    /// change the tempo at runtime without losing the current musical position, by snapshotting
+    /// the beats accumulated so far and resetting the underlying clock to measure from this instant
+    pub fn set_bpm (&mut self, bpm: f64) {
+        self.beat_offset = self.elapsed_beats();
+        self.nanos_per_beat = Self::nanos_per_beat_from_bpm(bpm);
+        self.clock.reset();
+    }
+
+    /// the elapsed time since the last tempo change, in nanoseconds
+    fn elapsed_nanos (&self) -> f64 {
+        return self.clock.elapsed().as_nanos() as f64;
+    }
+
+    /// convert a duration in nanoseconds to a whole number of ticks at the current tempo
+    pub fn nanos_to_ticks (&self, nanos: f64) -> u64 {
+        return (nanos / (self.nanos_per_beat / self.ticks_per_beat as f64)) as u64;
+    }
+
+    /// convert a duration in nanoseconds to a whole number of beats at the current tempo
+    pub fn nanos_to_beats (&self, nanos: f64) -> u64 {
+        return (nanos / self.nanos_per_beat) as u64;
+    }
+
+    /// convert a duration in nanoseconds to a whole number of bars at the current tempo
+    pub fn nanos_to_bars (&self, nanos: f64) -> u64 {
+        return (nanos / (self.nanos_per_beat * self.beats_per_bar as f64)) as u64;
+    }
+
+    /// convert a number of ticks to a duration in nanoseconds at the current tempo
+    pub fn ticks_to_nanos (&self, ticks: f64) -> f64 {
+        return ticks * (self.nanos_per_beat / self.ticks_per_beat as f64);
+    }
+
+    /// convert a number of beats to a duration in nanoseconds at the current tempo
+    pub fn beats_to_nanos (&self, beats: f64) -> f64 {
+        return beats * self.nanos_per_beat;
+    }
+
+    /// convert a number of bars to a duration in nanoseconds at the current tempo
+    pub fn bars_to_nanos (&self, bars: f64) -> f64 {
+        return bars * self.nanos_per_beat * self.beats_per_bar as f64;
+    }
+
+    /// the fractional number of beats elapsed, accounting for any past tempo changes
+    pub fn elapsed_beats (&self) -> f64 {
+        return self.beat_offset + (self.elapsed_nanos() / self.nanos_per_beat);
+    }
+
+    /// the fractional number of bars elapsed, accounting for any past tempo changes
+    pub fn elapsed_bars (&self) -> f64 {
+        return self.elapsed_beats() / self.beats_per_bar as f64;
+    }
+
+    /// the fractional number of ticks elapsed, accounting for any past tempo changes
+    pub fn elapsed_ticks (&self) -> f64 {
+        return self.elapsed_beats() * self.ticks_per_beat as f64;
+    }
+
+    /// the duration of a single beat at the current tempo, handy for configuring a `RevLimiter` to tick on beat boundaries
+    pub fn beat_interval (&self) -> Duration {
+        return Duration::from_nanos(self.nanos_per_beat as u64);
+    }
+
+    /// the duration of a single tick at the current tempo, handy for configuring a `RevLimiter` to tick on tick boundaries
+    pub fn tick_interval (&self) -> Duration {
+        return Duration::from_nanos((self.nanos_per_beat / self.ticks_per_beat as f64) as u64);
+    }
+
+}
+
+/// a smoothing factor for the exponential moving averages kept in `LoopState` (higher weighs recent frames more heavily)
+const LOOP_STATE_SMOOTHING: f64 = 0.1;
+
+/// a snapshot of a `RevLimiter`'s per-frame statistics
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoopState {
+
+    /// a monotonically increasing id for the current frame, starting at 0
+    pub frame_id: u64,
+
+    /// the delta time reported by the most recent call to `begin()`
+    pub delta: Duration,
+
+    /// `delta` expressed as a 64-bit float number of seconds
+    pub delta_seconds: f64,
+
+    /// the total `speed`-scaled, pause-aware simulation time elapsed as of the most recent `begin()`
+    pub elapsed: Duration,
+
+    /// frames per second, smoothed with an exponential moving average over recent frames
+    pub fps: f64,
+
+    /// the fraction of `interval` spent doing work between `begin()` and `next()`, smoothed with an exponential moving average
+    pub workload: f64,
+
+    /// the highest instantaneous `workload` ratio observed so far
+    pub peak_workload: f64,
+
 }
 
 /// an object that provides a means of controlling the rate at which a loop is run
-pub struct RevLimiter {
+pub struct RevLimiter<T: TimeSource = SystemTime> {
 
     /// whether or not each iteration advances by the same interval despite jitter (deterministic loops)
     pub lockstep_enabled: bool,
@@ -60,7 +335,7 @@ pub struct RevLimiter {
     pub interval: Duration,
 
     /// a clock for keeping track of time elapsed since the last iteration
-    pub clock: Clock,
+    pub clock: Clock<T>,
 
     /// the duration of lag incurred behind the real-world elapsed time
     pub lag: Duration,
@@ -68,39 +343,119 @@ pub struct RevLimiter {
     /// the ratio of passing time in the loop to passing real time
     pub speed: f64,
 
+    /// the accumulated real time not yet consumed by a fixed update
+    pub accumulator: Duration,
+
+    /// the maximum number of fixed updates `updates()` will yield in a single call, to avoid a spiral of death when a frame takes too long
+    pub max_updates: u32,
+
+    /// whether the loop is currently paused; while paused, `begin()` reports a zero delta and simulation time stops advancing
+    pub paused: bool,
+
+    /// the total unscaled real time elapsed since this rev limiter was created, in seconds (accumulated as `f64` to minimize rounding drift over long sessions)
+    raw_elapsed_seconds: f64,
+
+    /// the total `speed`-scaled, pause-aware simulation time elapsed since this rev limiter was created, in seconds
+    elapsed_seconds: f64,
+
+    /// the per-frame statistics snapshotted by `begin()`/`next()`, queryable via `state()`
+    state: LoopState,
+
+    /// the frame id to assign to the next call to `begin()`
+    next_frame_id: u64,
+
 }
 
-impl RevLimiter {
+/// the default cap on fixed updates drained per call to `updates()`
+const DEFAULT_MAX_UPDATES: u32 = 5;
+
+impl RevLimiter<SystemTime> {
 
-    /// create a new rev limiter
+    /// create a new rev limiter, backed by the system clock
     pub fn new (lockstep_enabled: bool, catchup_enabled: bool, interval_seconds: f64, speed: f64) -> Self {
-        return Self {
+        return Self::new_with_clock(
             lockstep_enabled,
             catchup_enabled,
-            interval: Duration::from_nanos((interval_seconds * 1_000_000_000f64) as u64),
-            clock: Clock::new(),
-            lag: Duration::new(0, 0),
+            seconds_to_duration(interval_seconds),
             speed,
-        };
+            Clock::new(),
+        );
     }
 
-    /// create a new rev limiter given a custom clock
+    /// create a new loop from a frequency (iterations per second) instead of an interval, backed by the system clock
+    pub fn new_from_frequency (lockstep_enabled: bool, catchup_enabled: bool, per_second: u32, speed: f64) -> Self {
+        return Self::new_with_clock(
+            lockstep_enabled,
+            catchup_enabled,
+            Duration::from_nanos(((1.0 / (per_second as f64)) * 1_000_000_000.0) as u64),
+            speed,
+            Clock::new(),
+        );
+    }
 
+}
 
-    /// create a new loop from a frequency (iterations per second) instead of an interval
-    pub fn new_from_frequency (lockstep_enabled: bool, catchup_enabled: bool, per_second: u32, speed: f64) -> Self {
+impl<T: TimeSource> RevLimiter<T> {
+
+    /// create a new rev limiter given a custom clock
+    pub fn new_with_clock (lockstep_enabled: bool, catchup_enabled: bool, interval: Duration, speed: f64, clock: Clock<T>) -> Self {
         return Self {
             lockstep_enabled,
             catchup_enabled,
-            interval: Duration::from_nanos(((1.0 / (per_second as f64)) * 1_000_000_000.0) as u64),
-            clock: Clock::new(),
+            interval,
+            clock,
             lag: Duration::new(0, 0),
             speed,
+            accumulator: Duration::new(0, 0),
+            max_updates: DEFAULT_MAX_UPDATES,
+            paused: false,
+            raw_elapsed_seconds: 0.0,
+            elapsed_seconds: 0.0,
+            state: LoopState::default(),
+            next_frame_id: 0,
         };
     }
 
+    /// the per-frame statistics as of the most recent `begin()`/`next()` call
+    pub fn state (&self) -> LoopState {
+        return self.state;
+    }
+
+    /// pause the loop: `begin()` will report a zero delta and simulation time will stop advancing until `resume()` is called
+    pub fn pause (&mut self) {
+        self.paused = true;
+    }
+
+    /// resume a paused loop
+    pub fn resume (&mut self) {
+        self.paused = false;
+    }
+
+    /// the total unscaled real time elapsed since this rev limiter was created
+    pub fn raw_elapsed (&self) -> Duration {
+        return seconds_to_duration(self.raw_elapsed_seconds);
+    }
+
+    /// the total unscaled real time elapsed since this rev limiter was created, in seconds
+    pub fn raw_elapsed_seconds (&self) -> f64 {
+        return self.raw_elapsed_seconds;
+    }
+
+    /// the total `speed`-scaled, pause-aware simulation time elapsed since this rev limiter was created
+    pub fn elapsed (&self) -> Duration {
+        return seconds_to_duration(self.elapsed_seconds);
+    }
+
+    /// the total `speed`-scaled, pause-aware simulation time elapsed since this rev limiter was created, in seconds
+    pub fn elapsed_seconds (&self) -> f64 {
+        return self.elapsed_seconds;
+    }
+
     /// call the callback, automatically calculating delta time
     fn get_delta (&self, current_elapsed: Duration) -> f64 {
+        if self.paused {
+            return 0.0;
+        }
         if self.lockstep_enabled {
             return duration_to_seconds(self.interval) * self.speed;
         }
@@ -166,22 +521,77 @@ impl RevLimiter {
 
     /// signal that execution for this iteration of the loop has started, mainly for the purpose of starting a timer
     pub fn begin (&mut self) -> f64 {
-        let delta = self.get_delta(self.clock.elapsed());
+        let current_elapsed = self.clock.elapsed();
+        let delta = self.get_delta(current_elapsed);
         self.clock.reset();
+        self.raw_elapsed_seconds += duration_to_seconds(current_elapsed);
+        if !self.paused {
+            self.accumulator += current_elapsed;
+            self.elapsed_seconds += delta;
+        }
+
+        let instant_fps = if current_elapsed > Duration::new(0, 0) {
+            1.0 / duration_to_seconds(current_elapsed)
+        } else {
+            self.state.fps
+        };
+
+        self.state.frame_id = self.next_frame_id;
+        self.next_frame_id += 1;
+        self.state.delta = seconds_to_duration(delta);
+        self.state.delta_seconds = delta;
+        self.state.elapsed = self.elapsed();
+        self.state.fps = ema(self.state.fps, instant_fps);
+
         return delta;
     }
 
+    /// drain whole fixed-update steps from the accumulator and return how many were drained, stopping at `max_updates`
+    /// to avoid a spiral of death when a frame takes too long to produce; steps left over past the cap stay in the accumulator
+    pub fn updates (&mut self) -> u32 {
+        let mut count = 0;
+        while self.accumulator >= self.interval && count < self.max_updates {
+            self.accumulator -= self.interval;
+            count += 1;
+        }
+        return count;
+    }
+
+    /// the fraction of a whole fixed-update step left over in the accumulator, in `[0, 1)` once `updates()` has drained
+    /// every whole step (1 or more when `max_updates` stopped the drain early),
+    /// for interpolating rendering between the previous and current simulation state;
+    /// returns `0.0` for a zero `interval` rather than dividing by zero
+    pub fn alpha (&self) -> f64 {
+        if self.interval == Duration::new(0, 0) {
+            return 0.0;
+        }
+        return duration_to_seconds(self.accumulator) / duration_to_seconds(self.interval);
+    }
+
+    /// set the cap on fixed updates `updates()` will yield in a single call
+    pub fn set_max_updates (&mut self, max_updates: u32) {
+        self.max_updates = max_updates;
+    }
+
     /// signal that execution for this iteration of the loop has completed, and prepare for the next iteration
     pub fn next (&mut self) -> Duration {
-        let wait = self.get_wait(self.clock.elapsed());
+        let busy = self.clock.elapsed();
+        let wait = self.get_wait(busy);
         self.clock.reset();
         self.update_lag(wait);
+
+        let instant_workload = duration_to_seconds(busy) / duration_to_seconds(self.interval);
+        self.state.workload = ema(self.state.workload, instant_workload);
+        if instant_workload > self.state.peak_workload {
+            self.state.peak_workload = instant_workload;
+        }
+
         return wait;
     }
 
     /// set the interval in seconds
     pub fn set_interval (&mut self, seconds: f64) {
-        self.interval = Duration::from_nanos((seconds * 1_000_000_000.0) as u64);
+        self.interval = seconds_to_duration(seconds);
     }
 
     /// set the frequency in iterations per second
@@ -189,4 +599,4 @@ impl RevLimiter {
         self.interval = Duration::from_nanos(((1.0 / (per_second as f64)) * 1_000_000_000.0) as u64);
     }
 
-}
\ No newline at end of file
+}
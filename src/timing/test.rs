@@ -0,0 +1,154 @@
+use super::*;
+
+/// build a `Clock` driven by a fresh `ManualClock` for deterministic, scripted time in tests
+fn manual_clock () -> Clock<ManualClock> {
+    return Clock::with_source(ManualClock::default());
+}
+
+/// build a `RevLimiter` driven by a fresh `ManualClock` for deterministic, scripted time in tests
+fn manual_limiter (lockstep_enabled: bool, catchup_enabled: bool, interval_seconds: f64, speed: f64) -> RevLimiter<ManualClock> {
+    return RevLimiter::new_with_clock(lockstep_enabled, catchup_enabled, seconds_to_duration(interval_seconds), speed, manual_clock());
+}
+
+/// build a `ClockSignature` driven by a fresh `ManualClock` for deterministic, scripted time in tests
+fn manual_signature (bpm: f64, ticks_per_beat: u32, beats_per_bar: u32) -> ClockSignature<ManualClock> {
+    return ClockSignature::with_clock(manual_clock(), bpm, ticks_per_beat, beats_per_bar);
+}
+
+#[test]
+fn clock_new_resolves_to_the_system_time_source_without_annotations () {
+    let clock = Clock::new();
+    assert!(clock.elapsed_seconds() >= 0.0);
+}
+
+#[test]
+fn rev_limiter_new_resolves_to_the_system_time_source_without_annotations () {
+    let mut limiter = RevLimiter::new(false, false, 1.0 / 60.0, 1.0);
+    limiter.begin();
+    assert!(limiter.state().delta_seconds >= 0.0);
+}
+
+#[test]
+fn clock_signature_new_resolves_to_the_system_time_source_without_annotations () {
+    let sig = ClockSignature::new(120.0, 4, 4);
+    assert!(sig.elapsed_beats() >= 0.0);
+}
+
+#[test]
+fn manual_clock_elapsed_tracks_advances () {
+    let clock = manual_clock();
+    clock.source.advance(Duration::from_millis(250));
+    assert_eq!(clock.elapsed(), Duration::from_millis(250));
+    assert!((clock.elapsed_seconds() - 0.25).abs() < 1e-9);
+}
+
+#[test]
+fn rev_limiter_with_manual_time_source_computes_delta_from_advanced_time () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.clock.source.advance(Duration::from_millis(500));
+    let delta = limiter.begin();
+    assert!((delta - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn accumulator_drains_whole_steps_and_tracks_alpha () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.clock.source.advance(Duration::from_millis(2500));
+    limiter.begin();
+    assert_eq!(limiter.updates(), 2);
+    assert!((limiter.alpha() - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn alpha_is_zero_for_a_zero_interval_instead_of_dividing_by_zero () {
+    let mut limiter = manual_limiter(false, false, 0.0, 1.0);
+    limiter.clock.source.advance(Duration::from_millis(500));
+    limiter.begin();
+    assert_eq!(limiter.alpha(), 0.0);
+}
+
+#[test]
+fn accumulator_caps_updates_at_max_updates () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.set_max_updates(2);
+    limiter.clock.source.advance(Duration::from_millis(5000));
+    limiter.begin();
+    assert_eq!(limiter.updates(), 2);
+}
+
+#[test]
+fn pause_freezes_simulation_time_but_not_raw_elapsed () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.clock.source.advance(Duration::from_millis(500));
+    limiter.begin();
+
+    limiter.pause();
+    limiter.clock.source.advance(Duration::from_millis(500));
+    let delta = limiter.begin();
+    assert_eq!(delta, 0.0);
+    assert!((limiter.raw_elapsed_seconds() - 1.0).abs() < 1e-9);
+    assert!((limiter.elapsed_seconds() - 0.5).abs() < 1e-9);
+
+    limiter.resume();
+    limiter.clock.source.advance(Duration::from_millis(250));
+    limiter.begin();
+    assert!((limiter.raw_elapsed_seconds() - 1.25).abs() < 1e-9);
+    assert!((limiter.elapsed_seconds() - 0.75).abs() < 1e-9);
+}
+
+#[test]
+fn loop_state_frame_id_starts_at_zero_and_increments () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.begin();
+    assert_eq!(limiter.state().frame_id, 0);
+    limiter.clock.source.advance(Duration::from_millis(100));
+    limiter.begin();
+    assert_eq!(limiter.state().frame_id, 1);
+}
+
+#[test]
+fn loop_state_tracks_workload_and_peak () {
+    let mut limiter = manual_limiter(false, false, 1.0, 1.0);
+    limiter.begin();
+    limiter.clock.source.advance(Duration::from_millis(500));
+    limiter.next();
+    assert!((limiter.state().workload - 0.05).abs() < 1e-9);
+    assert!((limiter.state().peak_workload - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn clock_signature_converts_nanos_to_beats_and_back () {
+    let sig = manual_signature(120.0, 4, 4);
+    // at 120 bpm, one beat lasts 0.5s = 500_000_000ns
+    assert_eq!(sig.nanos_to_beats(500_000_000.0), 1);
+    assert_eq!(sig.nanos_to_ticks(500_000_000.0), 4);
+    assert!((sig.beats_to_nanos(1.0) - 500_000_000.0).abs() < 1e-6);
+}
+
+#[test]
+fn clock_signature_set_bpm_preserves_musical_position () {
+    let mut sig = manual_signature(120.0, 4, 4);
+    sig.clock.source.advance(Duration::from_millis(1000));
+    assert!((sig.elapsed_beats() - 2.0).abs() < 1e-9);
+
+    sig.set_bpm(60.0);
+    assert!((sig.elapsed_beats() - 2.0).abs() < 1e-9);
+
+    sig.clock.source.advance(Duration::from_millis(1000));
+    assert!((sig.elapsed_beats() - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn elapsed_wrapped_stays_within_the_default_wrap_period () {
+    let clock = manual_clock();
+    clock.source.advance(Duration::from_secs(3700));
+    assert_eq!(clock.elapsed_wrapped(), Duration::from_secs(100));
+}
+
+#[test]
+fn set_wrap_period_changes_the_wrap_boundary () {
+    let mut clock = manual_clock();
+    clock.set_wrap_period(Duration::from_secs(10));
+    clock.source.advance(Duration::from_secs(25));
+    assert_eq!(clock.elapsed_wrapped(), Duration::from_secs(5));
+}